@@ -13,19 +13,31 @@
 
 //! This crate extends [Rusoto's](https://crates.io/crates/rusoto) existing authentication infrastructure to support this feature.
 
+use chrono::{DateTime, Utc};
 use dirs::home_dir;
+use ini::Ini;
 use lazy_static::lazy_static;
 use regex::Regex;
 use rusoto_core::{request::TlsError, Client, HttpClient, Region, RusotoError};
-use rusoto_credential::{AutoRefreshingProvider, CredentialsError, StaticProvider};
-use rusoto_sts::{StsAssumeRoleSessionCredentialsProvider, StsClient};
+use rusoto_credential::{
+    AutoRefreshingProvider, AwsCredentials, CredentialsError, DefaultCredentialsProvider,
+    ProvideAwsCredentials, StaticProvider,
+};
+use rusoto_sts::{
+    GetSessionTokenRequest, Sts, StsAssumeRoleSessionCredentialsProvider, StsClient,
+};
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
     env::{var, var_os, VarError},
     fmt::Display,
-    fs::File,
-    io::{BufRead, BufReader},
+    fs,
+    hash::{Hash, Hasher},
+    io::Write,
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
     path::{Path, PathBuf},
+    process::Command,
+    time::Duration,
 };
 use thiserror::Error;
 
@@ -34,24 +46,244 @@ lazy_static! {
         Regex::new(r"^\[(profile )?([^\]]+)\]$").expect("Failed to compile regex");
 }
 
-type StsAuthProvider = AutoRefreshingProvider<StsAssumeRoleSessionCredentialsProvider>;
+type RawAssumeRoleProvider = AutoRefreshingProvider<StsAssumeRoleSessionCredentialsProvider>;
 
+#[allow(clippy::too_many_arguments)]
 fn get_sts_auth_provider(
     client: StsClient,
     role_arn: &str,
-) -> Result<StsAuthProvider, StsClientError> {
+    role_session_name: Option<&str>,
+    duration_seconds: Option<i64>,
+    external_id: Option<&str>,
+    policy: Option<&str>,
+    mfa_serial: Option<&str>,
+) -> Result<RawAssumeRoleProvider, StsClientError> {
     let provider = StsAssumeRoleSessionCredentialsProvider::new(
         client,
         role_arn.to_string(),
-        "default".to_string(),
-        None,
-        None,
-        None,
-        None,
+        role_session_name.unwrap_or("default").to_string(),
+        external_id.map(ToString::to_string),
+        duration_seconds.map(|s| Duration::from_secs(s.max(0) as u64)),
+        policy.map(ToString::to_string),
+        mfa_serial.map(ToString::to_string),
     );
     AutoRefreshingProvider::new(provider).map_err(Into::into)
 }
 
+/// The JSON document a `credential_process` command is expected to print to stdout, per the
+/// [AWS CLI spec](https://docs.aws.amazon.com/cli/latest/userguide/cli-configure-sourcing-external.html).
+#[derive(Debug, Deserialize)]
+struct CredentialProcessOutput {
+    #[serde(rename = "AccessKeyId")]
+    access_key_id: String,
+    #[serde(rename = "SecretAccessKey")]
+    secret_access_key: String,
+    #[serde(rename = "SessionToken")]
+    session_token: Option<String>,
+    #[serde(rename = "Expiration")]
+    expiration: Option<String>,
+}
+
+/// Run a profile's `credential_process` command and parse the credentials it prints.
+fn run_credential_process(command: &str) -> Result<CredentialProcessOutput, StsClientError> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .output()
+        .map_err(|e| StsClientError::CredentialProcessError(e.to_string()))?;
+    if !output.status.success() {
+        return Err(StsClientError::CredentialProcessError(format!(
+            "`{}` exited with {}",
+            command, output.status
+        )));
+    }
+    serde_json::from_slice(&output.stdout)
+        .map_err(|e| StsClientError::CredentialProcessError(e.to_string()))
+}
+
+/// Credential provider for a profile's `credential_process` command: re-runs the command on
+/// every refresh rather than the single invocation `new_with_sts_region` used to make, so
+/// short-lived `credential_process` credentials (Vault, `aws-vault`, `saml2aws`, ...) keep
+/// working for the lifetime of a long-running client instead of going stale once `Expiration`
+/// passes. Wrapped in `AutoRefreshingProvider` by `new_with_sts_region`, which takes care of
+/// only calling `credentials()` again once the previous output has expired.
+#[derive(Clone, Debug)]
+struct CredentialProcessProvider {
+    command: String,
+}
+
+#[async_trait::async_trait]
+impl ProvideAwsCredentials for CredentialProcessProvider {
+    async fn credentials(&self) -> Result<AwsCredentials, CredentialsError> {
+        let output = run_credential_process(&self.command)
+            .map_err(|e| CredentialsError::new(e.to_string()))?;
+        let expires_at = output
+            .expiration
+            .as_deref()
+            .and_then(|e| DateTime::parse_from_rfc3339(e).ok())
+            .map(|dt| dt.with_timezone(&Utc));
+        Ok(AwsCredentials::new(
+            output.access_key_id,
+            output.secret_access_key,
+            output.session_token,
+            expires_at,
+        ))
+    }
+}
+
+/// A session obtained from STS (`GetSessionToken`, or `AssumeRole` via
+/// `StsAuthProvider::CachingAssumeRole`), persisted to disk so a still-valid session can be
+/// reused across process invocations instead of calling STS (and re-prompting for an MFA code)
+/// every time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedSessionCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: String,
+    expiration: String,
+}
+
+impl CachedSessionCredentials {
+    /// Whether `expiration` is still far enough in the future to be worth reusing, with a
+    /// minute of margin so a caller isn't handed credentials that expire mid-request.
+    fn is_valid(&self) -> bool {
+        DateTime::parse_from_rfc3339(&self.expiration)
+            .map(|exp| exp.with_timezone(&Utc) > Utc::now() + chrono::Duration::minutes(1))
+            .unwrap_or(false)
+    }
+}
+
+/// Path to the on-disk cache entry for `profile_name`'s session credentials, under
+/// `~/.aws/sts_profile_auth_cache/`.
+fn session_cache_path(profile_name: &str) -> Result<PathBuf, StsClientError> {
+    let mut path = home_dir().ok_or(StsClientError::NoHomeError)?;
+    path.push(".aws");
+    path.push("sts_profile_auth_cache");
+    path.push(format!("{}.json", profile_name));
+    Ok(path)
+}
+
+/// Cache key for a profile's assumed-role session, covering both the profile name and the
+/// session parameters (`role_session_name`/`duration_seconds`/`external_id`/`policy`) the
+/// resulting credentials are actually scoped by.
+///
+/// Distinct from the profile name alone (the key `get_session_token` caches its
+/// `GetSessionToken` session under) so an MFA session and an assumed-role session for the same
+/// profile don't collide in the same cache file; and distinct per parameter set so an inline
+/// `StsInstanceBuilder` override (e.g. a narrower `policy` or shorter `duration_seconds`) can't
+/// be handed back in place of -- or later pollute -- the profile's own default session.
+fn assumed_role_cache_key(
+    profile_name: &str,
+    role_session_name: Option<&str>,
+    duration_seconds: Option<i64>,
+    external_id: Option<&str>,
+    policy: Option<&str>,
+) -> String {
+    if role_session_name.is_none()
+        && duration_seconds.is_none()
+        && external_id.is_none()
+        && policy.is_none()
+    {
+        return format!("{profile_name}-assumed-role");
+    }
+    let mut hasher = DefaultHasher::new();
+    role_session_name.hash(&mut hasher);
+    duration_seconds.hash(&mut hasher);
+    external_id.hash(&mut hasher);
+    policy.hash(&mut hasher);
+    format!("{profile_name}-assumed-role-{:x}", hasher.finish())
+}
+
+fn load_cached_session(profile_name: &str) -> Option<CachedSessionCredentials> {
+    let path = session_cache_path(profile_name).ok()?;
+    let contents = fs::read_to_string(path).ok()?;
+    let cached: CachedSessionCredentials = serde_json::from_str(&contents).ok()?;
+    cached.is_valid().then_some(cached)
+}
+
+fn store_cached_session(
+    profile_name: &str,
+    credentials: &CachedSessionCredentials,
+) -> Result<(), StsClientError> {
+    let path = session_cache_path(profile_name)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| StsClientError::SessionCacheError(e.to_string()))?;
+    }
+    let contents = serde_json::to_string(credentials)
+        .map_err(|e| StsClientError::SessionCacheError(e.to_string()))?;
+    // The cache holds live session credentials, so (like the AWS CLI's own `~/.aws/cli/cache`)
+    // the file must not be left group/world-readable. `.mode(0o600)` only governs the
+    // permissions a *new* file is created with, so a stale cache entry left behind by an older
+    // version of this crate is explicitly rechmod'd too.
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&path)
+        .map_err(|e| StsClientError::SessionCacheError(e.to_string()))?;
+    file.set_permissions(fs::Permissions::from_mode(0o600))
+        .map_err(|e| StsClientError::SessionCacheError(e.to_string()))?;
+    (&file)
+        .write_all(contents.as_bytes())
+        .map_err(|e| StsClientError::SessionCacheError(e.to_string()))
+}
+
+/// Credential provider returned by `get_provider`/`get_provider_with`/`get_provider_with_mfa`:
+/// either the lazy AssumeRole provider, or one that additionally reuses a still-valid session
+/// from the on-disk cache in place of calling STS, and writes each fresh set of temporaries it
+/// obtains back to that same cache entry.
+///
+/// The cache is consulted on every `credentials()` call (not just once, at construction time),
+/// so a long-lived client built from `CachingAssumeRole` keeps working past the cached session's
+/// expiry: once the entry goes stale, the next call transparently falls through to `AssumeRole`
+/// again and refreshes it.
+pub enum StsAuthProvider {
+    AssumeRole(RawAssumeRoleProvider),
+    CachingAssumeRole {
+        provider: RawAssumeRoleProvider,
+        cache_key: String,
+    },
+}
+
+#[async_trait::async_trait]
+impl ProvideAwsCredentials for StsAuthProvider {
+    async fn credentials(&self) -> Result<AwsCredentials, CredentialsError> {
+        match self {
+            Self::AssumeRole(provider) => provider.credentials().await,
+            Self::CachingAssumeRole {
+                provider,
+                cache_key,
+            } => {
+                if let Some(cached) = load_cached_session(cache_key) {
+                    let expires_at = DateTime::parse_from_rfc3339(&cached.expiration)
+                        .ok()
+                        .map(|dt| dt.with_timezone(&Utc));
+                    return Ok(AwsCredentials::new(
+                        cached.access_key_id,
+                        cached.secret_access_key,
+                        Some(cached.session_token),
+                        expires_at,
+                    ));
+                }
+                let creds = provider.credentials().await?;
+                if let Some(expiration) = creds.expires_at() {
+                    let cached = CachedSessionCredentials {
+                        access_key_id: creds.aws_access_key_id().to_string(),
+                        secret_access_key: creds.aws_secret_access_key().to_string(),
+                        session_token: creds.token().clone().unwrap_or_default(),
+                        expiration: expiration.to_rfc3339(),
+                    };
+                    // Best-effort: a cache write failure shouldn't fail a call that already has
+                    // valid credentials in hand.
+                    let _ = store_cached_session(cache_key, &cached);
+                }
+                Ok(creds)
+            }
+        }
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum StsClientError {
     #[error("HttpClient init failed")]
@@ -64,6 +296,12 @@ pub enum StsClientError {
     CredentialsError(#[from] CredentialsError),
     #[error("RusotoError {0}")]
     RusotoError(String),
+    #[error("credential_process failed: {0}")]
+    CredentialProcessError(String),
+    #[error("session cache error: {0}")]
+    SessionCacheError(String),
+    #[error("failed to run command: {0}")]
+    ExecError(String),
 }
 
 impl<T: std::error::Error + 'static> From<RusotoError<T>> for StsClientError {
@@ -76,7 +314,10 @@ impl<T: std::error::Error + 'static> From<RusotoError<T>> for StsClientError {
 #[macro_export]
 macro_rules! get_client_sts_region_profile {
     ($T:ty, $region:expr, $profile:expr) => {
-        $crate::StsInstance::new($profile).and_then(|sts| {
+        $crate::get_client_sts_region_profile!($T, $region, $profile, None)
+    };
+    ($T:ty, $region:expr, $profile:expr, $sts_region:expr) => {
+        $crate::StsInstance::new_with_sts_region($profile, $sts_region).and_then(|sts| {
             let client = sts.get_client()?;
             let region = if let Some(r) = $region {
                 r
@@ -156,14 +397,53 @@ macro_rules! get_client_sts_with_profile {
     };
 }
 
+/// Macro to return a client with no credential resolution at all, for accessing public
+/// resources or local endpoints/mocks without requiring a populated `~/.aws`.
+///
+/// # Example usage:
+/// ``` ignore
+/// use rusoto_ec2::Ec2Client;
+/// use sts_profile_auth::get_client_sts_anonymous;
+/// use sts_profile_auth::StsClientError;
+///
+/// # fn main() -> Result<(), StsClientError> {
+/// let ec2 = get_client_sts_anonymous!(Ec2Client)?;
+/// # Ok(())
+/// # }
+/// ```
+#[macro_export]
+macro_rules! get_client_sts_anonymous {
+    ($T:ty) => {
+        $crate::StsInstance::anonymous().and_then(|sts| {
+            let client = sts.get_client()?;
+            Ok(<$T>::new_with_client(client, sts.get_region()))
+        })
+    };
+    ($T:ty, $region:expr) => {
+        $crate::StsInstance::anonymous().and_then(|sts| {
+            let client = sts.get_client()?;
+            Ok(<$T>::new_with_client(client, $region))
+        })
+    };
+}
+
 /// `StsInstance` contains an `StsClient` instance, and metadata used to create it (region, keys, role arn)
 #[derive(Clone)]
 pub struct StsInstance {
     sts_client: StsClient,
     region: Region,
+    sts_region: Region,
     aws_access_key_id: String,
     aws_secret_access_key: String,
+    aws_session_token: Option<String>,
     role_arn: Option<String>,
+    mfa_serial: Option<String>,
+    role_session_name: Option<String>,
+    duration_seconds: Option<i64>,
+    external_id: Option<String>,
+    policy: Option<String>,
+    anonymous: bool,
+    profile_name: String,
 }
 
 impl Default for StsInstance {
@@ -171,9 +451,18 @@ impl Default for StsInstance {
         Self {
             sts_client: StsClient::new(Region::default()),
             region: Region::default(),
+            sts_region: Region::default(),
             aws_access_key_id: "".to_string(),
             aws_secret_access_key: "".to_string(),
+            aws_session_token: None,
             role_arn: None,
+            mfa_serial: None,
+            role_session_name: None,
+            duration_seconds: None,
+            external_id: None,
+            policy: None,
+            anonymous: false,
+            profile_name: "default".to_string(),
         }
     }
 }
@@ -181,6 +470,21 @@ impl Default for StsInstance {
 impl StsInstance {
     /// Create a new `StsInstance`, either specifying a profile name, using the `AWS_PROFILE` environment variable, or using default
     pub fn new(profile_name: Option<&str>) -> Result<Self, StsClientError> {
+        Self::new_with_sts_region(profile_name, None)
+    }
+
+    /// Create a new `StsInstance`, additionally overriding the region used to contact the STS
+    /// endpoint itself. This is distinct from the region returned by `get_region()`, which is
+    /// used for the eventual service client: partitioned or opt-in-region setups often need to
+    /// assume a role through a "base" STS region while operating a client in another region.
+    ///
+    /// The override, when `None`, falls back to the profile's `sts_region` key, and then to the
+    /// same region `get_region()` returns (`AWS_REGION`/`AWS_DEFAULT_REGION`, else the profile's
+    /// `region`).
+    pub fn new_with_sts_region(
+        profile_name: Option<&str>,
+        sts_region: Option<Region>,
+    ) -> Result<Self, StsClientError> {
         let profiles = AwsProfileInfo::fill_profile_map()?;
         let profile_name = match profile_name {
             Some(n) => n.to_string(),
@@ -190,45 +494,317 @@ impl StsInstance {
         };
         let current_profile = profiles
             .get(&profile_name)
-            .ok_or_else(|| StsClientError::StsProfileError(profile_name))?;
+            .ok_or_else(|| StsClientError::StsProfileError(profile_name.clone()))?;
 
-        let region: Region = current_profile
-            .region
-            .parse()
+        let region: Region = var("AWS_REGION")
             .ok()
+            .or_else(|| var("AWS_DEFAULT_REGION").ok())
+            .and_then(|r| r.parse().ok())
+            .or_else(|| current_profile.region.parse().ok())
             .unwrap_or_default();
-        let (key, secret) = match current_profile.source_profile.as_ref() {
-            Some(prof) => {
-                let source_profile = profiles
-                    .get(prof)
-                    .ok_or_else(|| StsClientError::StsProfileError(prof.to_string()))?;
-                (
-                    source_profile.aws_access_key_id.to_string(),
-                    source_profile.aws_secret_access_key.to_string(),
-                )
-            }
-            None => (
-                current_profile.aws_access_key_id.to_string(),
-                current_profile.aws_secret_access_key.to_string(),
-            ),
+        let sts_region: Region = sts_region
+            .or_else(|| current_profile.sts_region.as_ref().and_then(|r| r.parse().ok()))
+            .unwrap_or_else(|| region.clone());
+        let (key, secret, session_token, role_chain, mut sts_client) = if let Some(command) =
+            current_profile.credential_process.as_ref()
+        {
+            let creds = run_credential_process(command)?;
+            // Unlike the static-key path below, this profile's credentials expire, so the raw
+            // one-shot output is only kept for the struct's own snapshot fields; the client
+            // itself is built from a provider that re-runs the command on every refresh.
+            let provider = AutoRefreshingProvider::new(CredentialProcessProvider {
+                command: command.clone(),
+            })?;
+            let sts_client = StsClient::new_with(HttpClient::new()?, provider, sts_region.clone());
+            (
+                creds.access_key_id,
+                creds.secret_access_key,
+                creds.session_token,
+                Vec::new(),
+                sts_client,
+            )
+        } else {
+            // `AwsProfileInfo::from_hashmap` resolved the `source_profile` chain down to its
+            // terminal static keys; `role_chain` carries every intermediate `AssumeRole` hop in
+            // between (outermost first), which we assume below in order so the client used to
+            // assume this profile's own `role_arn` reflects the innermost profile's session
+            // rather than the chain's raw base keys.
+            let key = current_profile.aws_access_key_id.to_string();
+            let secret = current_profile.aws_secret_access_key.to_string();
+            let session_token = current_profile.aws_session_token.clone();
+            let provider = StaticProvider::new(
+                key.to_string(),
+                secret.to_string(),
+                session_token.clone(),
+                None,
+            );
+            let sts_client = StsClient::new_with(HttpClient::new()?, provider, sts_region.clone());
+            (
+                key,
+                secret,
+                session_token,
+                current_profile.role_chain.clone(),
+                sts_client,
+            )
         };
-        let provider = StaticProvider::new_minimal(key.to_string(), secret.to_string());
+        for hop in &role_chain {
+            let hop_sts_region = hop
+                .sts_region
+                .as_ref()
+                .and_then(|r| r.parse().ok())
+                .unwrap_or_else(|| sts_region.clone());
+            let provider = get_sts_auth_provider(
+                sts_client,
+                &hop.role_arn,
+                hop.role_session_name.as_deref(),
+                hop.duration_seconds,
+                hop.external_id.as_deref(),
+                None,
+                hop.mfa_serial.as_deref(),
+            )?;
+            sts_client = StsClient::new_with(HttpClient::new()?, provider, hop_sts_region);
+        }
 
         Ok(Self {
-            sts_client: StsClient::new_with(HttpClient::new()?, provider, region.clone()),
+            sts_client,
             region,
+            sts_region,
             aws_access_key_id: key,
             aws_secret_access_key: secret,
+            aws_session_token: session_token,
             role_arn: current_profile.role_arn.clone(),
+            mfa_serial: current_profile.mfa_serial.clone(),
+            role_session_name: current_profile.role_session_name.clone(),
+            duration_seconds: current_profile.duration_seconds,
+            external_id: current_profile.external_id.clone(),
+            policy: None,
+            anonymous: false,
+            profile_name,
+        })
+    }
+
+    /// Build an `StsInstance` via an ordered fallback chain modeled on Rusoto's standard
+    /// credential providers: (1) the named profile (or `AWS_PROFILE`) parsed as by `new`, (2)
+    /// environment variables, (3) ECS container credentials, and (4) EC2 instance metadata.
+    ///
+    /// This lets callers work unchanged in CI, containers, and on EC2 hosts that have no
+    /// `~/.aws` file, rather than only on developer machines with a populated profile.
+    pub fn from_chain(profile_name: Option<&str>) -> Result<Self, StsClientError> {
+        match Self::new(profile_name) {
+            Ok(instance) => Ok(instance),
+            Err(StsClientError::StsProfileError(_) | StsClientError::NoHomeError) => {
+                let region = Region::default();
+                let provider = DefaultCredentialsProvider::new()?;
+                Ok(Self {
+                    sts_client: StsClient::new_with(HttpClient::new()?, provider, region.clone()),
+                    region: region.clone(),
+                    sts_region: region,
+                    aws_access_key_id: String::new(),
+                    aws_secret_access_key: String::new(),
+                    aws_session_token: None,
+                    role_arn: None,
+                    mfa_serial: None,
+                    role_session_name: None,
+                    duration_seconds: None,
+                    external_id: None,
+                    policy: None,
+                    anonymous: false,
+                    profile_name: profile_name.unwrap_or("default").to_string(),
+                })
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Build an `StsInstance` that skips credential resolution entirely, for local endpoints/mocks
+    /// (e.g. LocalStack) without requiring a populated `~/.aws`.
+    ///
+    /// `get_client` built from this instance signs requests with empty-string static
+    /// credentials rather than sending genuinely unsigned requests (Rusoto has no unsigned-request
+    /// mode), so this only works against endpoints that don't validate the SigV4 signature; it
+    /// will not, for example, read a public S3 object over HTTPS.
+    pub fn anonymous() -> Result<Self, StsClientError> {
+        let region = Region::default();
+        let provider = StaticProvider::new_minimal(String::new(), String::new());
+        Ok(Self {
+            sts_client: StsClient::new_with(HttpClient::new()?, provider, region.clone()),
+            region: region.clone(),
+            sts_region: region,
+            aws_access_key_id: String::new(),
+            aws_secret_access_key: String::new(),
+            aws_session_token: None,
+            role_arn: None,
+            mfa_serial: None,
+            role_session_name: None,
+            duration_seconds: None,
+            external_id: None,
+            policy: None,
+            anonymous: true,
+            profile_name: "anonymous".to_string(),
         })
     }
 
-    /// Get an auto-refreshing credential provider
+    /// Get a credential provider for this profile's configured `role_arn`.
+    ///
+    /// The returned provider reuses a still-valid `AssumeRole` session for this profile and
+    /// these session parameters from its own on-disk cache entry (separate from the one
+    /// `get_session_token` uses for `GetSessionToken` sessions, and from any other
+    /// `role_session_name`/`duration_seconds`/`external_id`/`policy` combination this profile
+    /// might be assumed with, so none of them can be handed back in place of each other) rather
+    /// than calling STS on every invocation; it transparently falls through to a fresh
+    /// `AssumeRole` call -- refreshing that cache entry -- once the cached session expires.
     pub fn get_provider(&self) -> Result<Option<StsAuthProvider>, StsClientError> {
         match &self.role_arn {
             Some(role_arn) => {
-                let provider = get_sts_auth_provider(self.sts_client.clone(), role_arn)?;
-                Ok(Some(provider))
+                let cache_key = assumed_role_cache_key(
+                    &self.profile_name,
+                    self.role_session_name.as_deref(),
+                    self.duration_seconds,
+                    self.external_id.as_deref(),
+                    self.policy.as_deref(),
+                );
+                let provider = get_sts_auth_provider(
+                    self.sts_client.clone(),
+                    role_arn,
+                    self.role_session_name.as_deref(),
+                    self.duration_seconds,
+                    self.external_id.as_deref(),
+                    self.policy.as_deref(),
+                    self.mfa_serial.as_deref(),
+                )?;
+                Ok(Some(StsAuthProvider::CachingAssumeRole {
+                    provider,
+                    cache_key,
+                }))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Get an auto-refreshing credential provider, overriding this instance's session name,
+    /// duration, external ID, and policy for a single call. Any argument left `None` falls back
+    /// to the value configured on the instance (see `StsInstanceBuilder`).
+    ///
+    /// Unlike `get_provider`, this always calls STS: a one-off override isn't read from or
+    /// written to the profile's session cache, since doing so could hand a later default
+    /// `get_provider()` call session parameters it never asked for.
+    pub fn get_provider_with(
+        &self,
+        role_session_name: Option<&str>,
+        duration_seconds: Option<i64>,
+        external_id: Option<&str>,
+        policy: Option<&str>,
+    ) -> Result<Option<StsAuthProvider>, StsClientError> {
+        match &self.role_arn {
+            Some(role_arn) => {
+                let provider = get_sts_auth_provider(
+                    self.sts_client.clone(),
+                    role_arn,
+                    role_session_name.or(self.role_session_name.as_deref()),
+                    duration_seconds.or(self.duration_seconds),
+                    external_id.or(self.external_id.as_deref()),
+                    policy.or(self.policy.as_deref()),
+                    self.mfa_serial.as_deref(),
+                )?;
+                Ok(Some(StsAuthProvider::AssumeRole(provider)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Obtain temporary credentials from STS `GetSessionToken`, authenticated with an MFA
+    /// device.
+    ///
+    /// `token_code` is the current six-digit code from the device named by the profile's
+    /// `mfa_serial`. The resulting access key, secret key, and session token are wrapped in a
+    /// `StaticProvider` so they can be used to build clients, or as the base credentials for a
+    /// subsequent assume-role call.
+    ///
+    /// A still-valid session for this profile is read from the on-disk cache (see
+    /// `cached_session_expiration`) rather than hitting STS and re-prompting for a token code;
+    /// a fresh session obtained from STS is written back to that same cache.
+    pub async fn get_session_token(
+        &self,
+        token_code: &str,
+    ) -> Result<StaticProvider, StsClientError> {
+        if let Some(cached) = load_cached_session(&self.profile_name) {
+            return Ok(StaticProvider::new(
+                cached.access_key_id,
+                cached.secret_access_key,
+                Some(cached.session_token),
+                None,
+            ));
+        }
+
+        let serial_number = self.mfa_serial.clone().ok_or_else(|| {
+            StsClientError::StsProfileError("No mfa_serial configured for profile".to_string())
+        })?;
+        let request = GetSessionTokenRequest {
+            serial_number: Some(serial_number),
+            token_code: Some(token_code.to_string()),
+            ..GetSessionTokenRequest::default()
+        };
+        let credentials = self
+            .sts_client
+            .get_session_token(request)
+            .await?
+            .credentials
+            .ok_or_else(|| {
+                StsClientError::RusotoError("No credentials returned by GetSessionToken".to_string())
+            })?;
+        let cached = CachedSessionCredentials {
+            access_key_id: credentials.access_key_id,
+            secret_access_key: credentials.secret_access_key,
+            session_token: credentials.session_token,
+            expiration: credentials.expiration,
+        };
+        store_cached_session(&self.profile_name, &cached)?;
+        Ok(StaticProvider::new(
+            cached.access_key_id,
+            cached.secret_access_key,
+            Some(cached.session_token),
+            None,
+        ))
+    }
+
+    /// Expiry timestamp (RFC3339) of this profile's cached session, if a cache entry exists,
+    /// regardless of whether it is still valid. Callers can compare this against the current
+    /// time to decide whether the next `get_session_token` call will need a fresh MFA code.
+    pub fn cached_session_expiration(&self) -> Option<String> {
+        let path = session_cache_path(&self.profile_name).ok()?;
+        let contents = fs::read_to_string(path).ok()?;
+        let cached: CachedSessionCredentials = serde_json::from_str(&contents).ok()?;
+        Some(cached.expiration)
+    }
+
+    /// Get an auto-refreshing assume-role provider whose base credentials are an MFA-authenticated
+    /// session token, for roles whose `source_profile` itself requires MFA before `AssumeRole`
+    /// can be called.
+    ///
+    /// `token_code` is the current six-digit code from the device named by the profile's
+    /// `mfa_serial`.
+    pub async fn get_provider_with_mfa(
+        &self,
+        token_code: &str,
+    ) -> Result<Option<StsAuthProvider>, StsClientError> {
+        match &self.role_arn {
+            Some(role_arn) => {
+                let session_provider = self.get_session_token(token_code).await?;
+                let client = StsClient::new_with(
+                    HttpClient::new()?,
+                    session_provider,
+                    self.sts_region.clone(),
+                );
+                let provider = get_sts_auth_provider(
+                    client,
+                    role_arn,
+                    self.role_session_name.as_deref(),
+                    self.duration_seconds,
+                    self.external_id.as_deref(),
+                    self.policy.as_deref(),
+                    self.mfa_serial.as_deref(),
+                )?;
+                Ok(Some(StsAuthProvider::AssumeRole(provider)))
             }
             None => Ok(None),
         }
@@ -238,6 +814,12 @@ impl StsInstance {
     pub fn get_client(&self) -> Result<Client, StsClientError> {
         let client = match self.get_provider()? {
             Some(provider) => Client::new_with(provider, rusoto_core::HttpClient::new()?),
+            None if self.anonymous => {
+                // Empty-string static credentials, not a genuinely unsigned request -- see the
+                // limitation noted on `anonymous()`.
+                let provider = StaticProvider::new_minimal(String::new(), String::new());
+                Client::new_with(provider, rusoto_core::HttpClient::new()?)
+            }
             None => Client::shared(),
         };
         Ok(client)
@@ -246,6 +828,116 @@ impl StsInstance {
     pub fn get_region(&self) -> Region {
         self.region.clone()
     }
+
+    /// Resolve this instance's effective credentials — including assumed-role or session-token
+    /// temporaries — as the `AWS_ACCESS_KEY_ID`/`AWS_SECRET_ACCESS_KEY`/`AWS_SESSION_TOKEN`/
+    /// `AWS_DEFAULT_REGION` environment variables a subprocess expects.
+    pub async fn credentials_as_env(&self) -> Result<Vec<(String, String)>, StsClientError> {
+        let (access_key, secret_key, session_token) = match self.get_provider()? {
+            Some(provider) => {
+                let creds = provider.credentials().await?;
+                (
+                    creds.aws_access_key_id().to_string(),
+                    creds.aws_secret_access_key().to_string(),
+                    creds.token().clone(),
+                )
+            }
+            None => (
+                self.aws_access_key_id.clone(),
+                self.aws_secret_access_key.clone(),
+                self.aws_session_token.clone(),
+            ),
+        };
+        let mut env = vec![
+            ("AWS_ACCESS_KEY_ID".to_string(), access_key),
+            ("AWS_SECRET_ACCESS_KEY".to_string(), secret_key),
+            (
+                "AWS_DEFAULT_REGION".to_string(),
+                self.region.name().to_string(),
+            ),
+        ];
+        if let Some(session_token) = session_token {
+            env.push(("AWS_SESSION_TOKEN".to_string(), session_token));
+        }
+        Ok(env)
+    }
+
+    /// Spawn `cmd` with this instance's effective credentials (see `credentials_as_env`)
+    /// injected into its environment. This lets the crate act as a credential wrapper for
+    /// arbitrary tools (terraform, kubectl, a user shell) in addition to constructing Rusoto
+    /// clients.
+    pub async fn exec(
+        &self,
+        cmd: &str,
+        args: &[&str],
+    ) -> Result<std::process::ExitStatus, StsClientError> {
+        let env = self.credentials_as_env().await?;
+        Command::new(cmd)
+            .args(args)
+            .envs(env)
+            .status()
+            .map_err(|e| StsClientError::ExecError(e.to_string()))
+    }
+}
+
+/// Builder for the AssumeRole session parameters used by `StsInstance::get_provider`:
+/// `role_session_name`, `duration_seconds`, `external_id`, and an inline scoping `policy`.
+///
+/// Any parameter left unset falls back to the value read from the profile (e.g.
+/// `role_session_name`/`duration_seconds`/`external_id` keys in `~/.aws/config`), and finally
+/// to `StsAssumeRoleSessionCredentialsProvider`'s own defaults.
+#[derive(Default, Clone)]
+pub struct StsInstanceBuilder {
+    role_session_name: Option<String>,
+    duration_seconds: Option<i64>,
+    external_id: Option<String>,
+    policy: Option<String>,
+}
+
+impl StsInstanceBuilder {
+    pub fn role_session_name(mut self, role_session_name: impl Into<String>) -> Self {
+        self.role_session_name = Some(role_session_name.into());
+        self
+    }
+
+    pub fn duration_seconds(mut self, duration_seconds: i64) -> Self {
+        self.duration_seconds = Some(duration_seconds);
+        self
+    }
+
+    pub fn external_id(mut self, external_id: impl Into<String>) -> Self {
+        self.external_id = Some(external_id.into());
+        self
+    }
+
+    pub fn policy(mut self, policy: impl Into<String>) -> Self {
+        self.policy = Some(policy.into());
+        self
+    }
+
+    /// Build the `StsInstance`, overriding any values read from the profile with the ones set
+    /// on this builder.
+    pub fn build(self, profile_name: Option<&str>) -> Result<StsInstance, StsClientError> {
+        let mut instance = StsInstance::new(profile_name)?;
+        instance.role_session_name = self.role_session_name.or(instance.role_session_name);
+        instance.duration_seconds = self.duration_seconds.or(instance.duration_seconds);
+        instance.external_id = self.external_id.or(instance.external_id);
+        instance.policy = self.policy;
+        Ok(instance)
+    }
+}
+
+/// One intermediate `AssumeRole` hop in a `source_profile` chain: the role to assume, and the
+/// session parameters/region to assume it with, before moving on to the next profile in the
+/// chain.
+#[derive(Clone, Debug)]
+struct ChainedRoleHop {
+    role_arn: String,
+    mfa_serial: Option<String>,
+    role_session_name: Option<String>,
+    duration_seconds: Option<i64>,
+    external_id: Option<String>,
+    sts_region: Option<String>,
 }
 
 /// Profile meta-data, representing either a profile with an access key, or a profile utilizing sts.
@@ -255,8 +947,21 @@ pub struct AwsProfileInfo {
     pub region: String,
     pub aws_access_key_id: String,
     pub aws_secret_access_key: String,
+    role_chain: Vec<ChainedRoleHop>,
     pub role_arn: Option<String>,
     pub source_profile: Option<String>,
+    pub mfa_serial: Option<String>,
+    pub credential_process: Option<String>,
+    pub sts_region: Option<String>,
+    pub role_session_name: Option<String>,
+    pub duration_seconds: Option<i64>,
+    pub external_id: Option<String>,
+    pub sso_session: Option<String>,
+    pub sso_start_url: Option<String>,
+    pub sso_region: Option<String>,
+    pub sso_account_id: Option<String>,
+    pub sso_role_name: Option<String>,
+    pub aws_session_token: Option<String>,
 }
 
 impl AwsProfileInfo {
@@ -278,60 +983,88 @@ impl AwsProfileInfo {
 
         let source_profile = prof_map.get("source_profile").map(ToString::to_string);
         let role_arn = prof_map.get("role_arn").map(ToString::to_string);
-        let mut access_key = prof_map.get("aws_access_key_id").map(ToString::to_string);
-        let mut access_secret = prof_map
+        let mfa_serial = prof_map.get("mfa_serial").map(ToString::to_string);
+        let credential_process = prof_map.get("credential_process").map(ToString::to_string);
+        let sts_region = prof_map.get("sts_region").map(ToString::to_string);
+        let role_session_name = prof_map.get("role_session_name").map(ToString::to_string);
+        let duration_seconds = prof_map.get("duration_seconds").and_then(|v| v.parse().ok());
+        let external_id = prof_map.get("external_id").map(ToString::to_string);
+        let sso_session = prof_map.get("sso_session").map(ToString::to_string);
+        let sso_start_url = prof_map.get("sso_start_url").map(ToString::to_string);
+        let sso_region = prof_map.get("sso_region").map(ToString::to_string);
+        let sso_account_id = prof_map.get("sso_account_id").map(ToString::to_string);
+        let sso_role_name = prof_map.get("sso_role_name").map(ToString::to_string);
+        let aws_session_token = prof_map.get("aws_session_token").map(ToString::to_string);
+
+        let access_key = prof_map.get("aws_access_key_id").map(ToString::to_string);
+        let access_secret = prof_map
             .get("aws_secret_access_key")
             .map(ToString::to_string);
 
-        if let Some(s) = source_profile.as_ref() {
-            let pmap = match profile_map.get(s) {
-                Some(p) => p,
-                None => return None,
-            };
-            pmap.get("aws_access_key_id")
-                .map(|a| access_key.replace(a.to_string()));
-            pmap.get("aws_secret_access_key")
-                .map(|a| access_secret.replace(a.to_string()));
-        }
-        let aws_access_key_id = match access_key {
-            Some(a) => a,
-            None => return None,
-        };
-        let aws_secret_access_key = match access_secret {
-            Some(a) => a,
-            None => return None,
+        let (aws_access_key_id, aws_secret_access_key, role_chain) = if credential_process.is_some()
+        {
+            (
+                access_key.unwrap_or_default(),
+                access_secret.unwrap_or_default(),
+                Vec::new(),
+            )
+        } else if let Some(source) = source_profile.as_ref() {
+            resolve_chained_credentials(profile_map, source)?
+        } else {
+            match (access_key, access_secret) {
+                (Some(k), Some(s)) => (k, s, Vec::new()),
+                _ => return None,
+            }
         };
         Some(Self {
             name,
             region,
             aws_access_key_id,
             aws_secret_access_key,
+            role_chain,
             role_arn,
             source_profile,
+            mfa_serial,
+            credential_process,
+            sts_region,
+            role_session_name,
+            duration_seconds,
+            external_id,
+            sso_session,
+            sso_start_url,
+            sso_region,
+            sso_account_id,
+            sso_role_name,
+            aws_session_token,
         })
     }
 
     /// Extract profile information hashmap from `${HOME}/.aws/config` and `${HOME}/.aws/credentials`
     pub fn fill_profile_map() -> Result<HashMap<String, Self>, StsClientError> {
-        let config_dir = if let Some(s) = var_os("AWS_CONFIG_FILE") {
-            PathBuf::from(s)
-        } else if let Some(h) = home_dir() {
-            h.join(".aws")
-        } else {
-            return Err(StsClientError::NoHomeError);
-        };
+        let home_aws_dir = home_dir().map(|h| h.join(".aws"));
 
-        let config_file = config_dir.join("config");
-        let credential_file = config_dir.join("credentials");
+        let config_file = match var_os("AWS_CONFIG_FILE") {
+            Some(s) => Some(PathBuf::from(s)),
+            None => home_aws_dir.as_ref().map(|d| d.join("config")),
+        };
+        let credential_file = match var_os("AWS_SHARED_CREDENTIALS_FILE")
+            .or_else(|| var_os("AWS_CREDENTIALS_FILE"))
+        {
+            Some(s) => Some(PathBuf::from(s)),
+            None => home_aws_dir.as_ref().map(|d| d.join("credentials")),
+        };
+        if config_file.is_none() && credential_file.is_none() {
+            return Err(StsClientError::NoHomeError);
+        }
 
         let mut profile_map: HashMap<String, HashMap<String, String>> = HashMap::new();
 
-        for fname in &[config_file, credential_file] {
-            if !Path::new(fname).exists() {
+        for fname in [config_file, credential_file].into_iter().flatten() {
+            if !fname.exists() {
                 continue;
             }
 
-            if let Some(p) = parse_config_file(fname) {
+            if let Some(p) = parse_config_file(&fname) {
                 if profile_map.is_empty() {
                     profile_map = p;
                 } else {
@@ -347,6 +1080,11 @@ impl AwsProfileInfo {
                 }
             }
         }
+
+        insert_env_credentials_profile(&mut profile_map);
+
+        resolve_sso_sessions(&mut profile_map);
+
         let profile_map: HashMap<_, _> = profile_map
             .keys()
             .filter_map(|k| Self::from_hashmap(k, &profile_map).map(|p| (k.to_string(), p)))
@@ -356,57 +1094,144 @@ impl AwsProfileInfo {
     }
 }
 
-/// Stolen from rusoto credential's profile.rs
-/// Parses an aws credentials config file and returns a hashmap of hashmaps.
+/// Follow a profile's `source_profile` chain (a role profile whose source profile is itself a
+/// role, and so on) until a profile carrying static keys is found, collecting every intermediate
+/// `AssumeRole` hop along the way (outermost/base-facing first).
+///
+/// A profile in the middle of the chain is itself an assumed-role profile (it has a `role_arn`
+/// but no static keys of its own), so its effective credentials can only be obtained by assuming
+/// that role. Returning just the terminal static keys and leaving it at that would let a caller
+/// skip straight from the base keys to the final role, silently assuming the wrong identity for
+/// every hop in between; callers must assume each returned hop in order instead.
+fn resolve_chained_credentials(
+    profile_map: &HashMap<String, HashMap<String, String>>,
+    start: &str,
+) -> Option<(String, String, Vec<ChainedRoleHop>)> {
+    let mut hops = Vec::new();
+    let mut seen = HashSet::new();
+    let mut current = start;
+    loop {
+        if !seen.insert(current) {
+            return None;
+        }
+        let prof_map = profile_map.get(current)?;
+        if let (Some(key), Some(secret)) = (
+            prof_map.get("aws_access_key_id"),
+            prof_map.get("aws_secret_access_key"),
+        ) {
+            hops.reverse();
+            return Some((key.to_string(), secret.to_string(), hops));
+        }
+        hops.push(ChainedRoleHop {
+            role_arn: prof_map.get("role_arn")?.to_string(),
+            mfa_serial: prof_map.get("mfa_serial").map(ToString::to_string),
+            role_session_name: prof_map.get("role_session_name").map(ToString::to_string),
+            duration_seconds: prof_map.get("duration_seconds").and_then(|v| v.parse().ok()),
+            external_id: prof_map.get("external_id").map(ToString::to_string),
+            sts_region: prof_map.get("sts_region").map(ToString::to_string),
+        });
+        current = prof_map.get("source_profile")?;
+    }
+}
+
+/// When no `default` profile was parsed from the config/credentials files (e.g. there are no
+/// dotfiles at all), synthesize one from `AWS_ACCESS_KEY_ID`/`AWS_SECRET_ACCESS_KEY`/
+/// `AWS_SESSION_TOKEN`/`AWS_REGION`/`AWS_DEFAULT_REGION`, so the crate works in CI and container
+/// environments that only set environment variables.
+fn insert_env_credentials_profile(profile_map: &mut HashMap<String, HashMap<String, String>>) {
+    if profile_map.contains_key("default") {
+        return;
+    }
+    let (Ok(access_key_id), Ok(secret_access_key)) =
+        (var("AWS_ACCESS_KEY_ID"), var("AWS_SECRET_ACCESS_KEY"))
+    else {
+        return;
+    };
+    let mut values = HashMap::new();
+    values.insert("aws_access_key_id".to_string(), access_key_id);
+    values.insert("aws_secret_access_key".to_string(), secret_access_key);
+    if let Ok(session_token) = var("AWS_SESSION_TOKEN") {
+        values.insert("aws_session_token".to_string(), session_token);
+    }
+    if let Ok(region) = var("AWS_REGION").or_else(|_| var("AWS_DEFAULT_REGION")) {
+        values.insert("region".to_string(), region);
+    }
+    profile_map.insert("default".to_string(), values);
+}
+
+/// Resolve each profile's `sso_session` reference to its `[sso-session name]` block, copying in
+/// `sso_start_url`/`sso_region`/`sso_account_id`/`sso_role_name` when the profile doesn't already
+/// set them directly.
+fn resolve_sso_sessions(profile_map: &mut HashMap<String, HashMap<String, String>>) {
+    const SSO_SESSION_KEYS: &[&str] = &[
+        "sso_start_url",
+        "sso_region",
+        "sso_account_id",
+        "sso_role_name",
+    ];
+    let sso_sessions: HashMap<String, HashMap<String, String>> = profile_map
+        .iter()
+        .filter_map(|(name, values)| {
+            name.strip_prefix("sso-session ")
+                .map(|session_name| (session_name.to_string(), values.clone()))
+        })
+        .collect();
+    for profile in profile_map.values_mut() {
+        let Some(session_name) = profile.get("sso_session").cloned() else {
+            continue;
+        };
+        let Some(session) = sso_sessions.get(&session_name) else {
+            continue;
+        };
+        for key in SSO_SESSION_KEYS {
+            if profile.contains_key(*key) {
+                continue;
+            }
+            if let Some(value) = session.get(*key) {
+                profile.insert((*key).to_string(), value.clone());
+            }
+        }
+    }
+}
+
+/// Parse an AWS config-style INI file (`~/.aws/config` or `~/.aws/credentials`) into a map of
+/// section name to key/value pairs. `[profile name]` headers are reduced to `name`; `[sso-session
+/// name]` headers are kept verbatim so `resolve_sso_sessions` can find them.
 fn parse_config_file<P>(file_path: P) -> Option<HashMap<String, HashMap<String, String>>>
 where
     P: AsRef<Path>,
 {
-    if !file_path.as_ref().exists() || !file_path.as_ref().is_file() {
+    let file_path = file_path.as_ref();
+    if !file_path.exists() || !file_path.is_file() {
         return None;
     }
 
-    let file = File::open(file_path).expect("expected file");
-    let file_lines = BufReader::new(&file);
-    let result: (HashMap<String, HashMap<String, String>>, Option<String>) = file_lines
-        .lines()
-        .filter_map(|line| {
-            line.ok()
-                .map(|l| l.trim_matches(' ').to_owned())
-                .into_iter()
-                .find(|l| !l.starts_with('#') && !l.is_empty())
-        })
-        .fold(Default::default(), |(mut result, profile), line| {
-            if PROFILE_REGEX.is_match(&line) {
-                let caps = PROFILE_REGEX.captures(&line).unwrap();
-                let next_profile = caps.get(2).map(|value| value.as_str().to_string());
-                (result, next_profile)
-            } else {
-                match &line
-                    .splitn(2, '=')
-                    .map(|value| value.trim_matches(' '))
-                    .collect::<Vec<&str>>()[..]
-                {
-                    [key, value] if !key.is_empty() && !value.is_empty() => {
-                        if let Some(current) = profile.clone() {
-                            let values = result.entry(current).or_insert_with(HashMap::new);
-                            (*values).insert((*key).to_string(), (*value).to_string());
-                        }
-                        (result, profile)
-                    }
-                    _ => (result, profile),
-                }
-            }
-        });
-    Some(result.0)
+    let ini = Ini::load_from_file(file_path).ok()?;
+    let mut result: HashMap<String, HashMap<String, String>> = HashMap::new();
+    for (section, props) in ini.iter() {
+        let section = section.unwrap_or_default();
+        let name = PROFILE_REGEX
+            .captures(&format!("[{}]", section))
+            .and_then(|caps| caps.get(2))
+            .map_or_else(|| section.to_string(), |m| m.as_str().to_string());
+        let values = result.entry(name).or_insert_with(HashMap::new);
+        for (key, value) in props.iter() {
+            values.insert(key.to_string(), value.to_string());
+        }
+    }
+    Some(result)
 }
 
 #[cfg(test)]
 mod tests {
     use rusoto_core::Region;
     use rusoto_ec2::{DescribeInstancesRequest, Ec2, Ec2Client};
+    use std::collections::HashMap;
 
-    use crate::{AwsProfileInfo, StsClientError};
+    use crate::{
+        insert_env_credentials_profile, parse_config_file, resolve_chained_credentials,
+        resolve_sso_sessions, AwsProfileInfo, CachedSessionCredentials, StsClientError,
+    };
 
     #[test]
     #[ignore]
@@ -447,4 +1272,162 @@ mod tests {
         assert!(instances.len() > 0);
         Ok(())
     }
+
+    fn profile(values: &[(&str, &str)]) -> HashMap<String, String> {
+        values
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve_chained_credentials_multi_hop() {
+        let mut profile_map = HashMap::new();
+        profile_map.insert(
+            "base".to_string(),
+            profile(&[
+                ("aws_access_key_id", "BASEKEY"),
+                ("aws_secret_access_key", "BASESECRET"),
+            ]),
+        );
+        profile_map.insert(
+            "middle".to_string(),
+            profile(&[
+                ("role_arn", "arn:aws:iam::111111111111:role/middle"),
+                ("source_profile", "base"),
+            ]),
+        );
+        profile_map.insert(
+            "leaf".to_string(),
+            profile(&[
+                ("role_arn", "arn:aws:iam::222222222222:role/leaf"),
+                ("source_profile", "middle"),
+            ]),
+        );
+
+        // `AwsProfileInfo::from_hashmap` calls this with the *source* profile of the one being
+        // built, so resolving "leaf"'s chain starts at "middle".
+        let (key, secret, hops) = resolve_chained_credentials(&profile_map, "middle")
+            .expect("chain should resolve to base's static keys");
+        assert_eq!(key, "BASEKEY");
+        assert_eq!(secret, "BASESECRET");
+        // Outermost (base-facing) hop first: assume "middle"'s role before "leaf"'s own.
+        assert_eq!(hops.len(), 1);
+        assert_eq!(hops[0].role_arn, "arn:aws:iam::111111111111:role/middle");
+    }
+
+    #[test]
+    fn test_resolve_chained_credentials_cycle_guard() {
+        let mut profile_map = HashMap::new();
+        profile_map.insert(
+            "a".to_string(),
+            profile(&[
+                ("role_arn", "arn:aws:iam::111111111111:role/a"),
+                ("source_profile", "b"),
+            ]),
+        );
+        profile_map.insert(
+            "b".to_string(),
+            profile(&[
+                ("role_arn", "arn:aws:iam::222222222222:role/b"),
+                ("source_profile", "a"),
+            ]),
+        );
+
+        assert!(resolve_chained_credentials(&profile_map, "a").is_none());
+    }
+
+    #[test]
+    fn test_insert_env_credentials_profile_fallback() {
+        let mut profile_map = HashMap::new();
+        insert_env_credentials_profile(&mut profile_map);
+        // No AWS_ACCESS_KEY_ID/AWS_SECRET_ACCESS_KEY set in this test process: nothing synthesized.
+        assert!(!profile_map.contains_key("default"));
+
+        let mut profile_map = HashMap::new();
+        profile_map.insert("default".to_string(), HashMap::new());
+        insert_env_credentials_profile(&mut profile_map);
+        // An existing `default` profile (even an empty one from the config files) is left alone.
+        assert!(profile_map["default"].is_empty());
+    }
+
+    #[test]
+    fn test_resolve_sso_sessions() {
+        let mut profile_map = HashMap::new();
+        profile_map.insert(
+            "sso-session my-sso".to_string(),
+            profile(&[
+                ("sso_start_url", "https://example.awsapps.com/start"),
+                ("sso_region", "us-east-1"),
+                ("sso_account_id", "999999999999"),
+            ]),
+        );
+        profile_map.insert(
+            "dev".to_string(),
+            profile(&[("sso_session", "my-sso"), ("sso_account_id", "123456789012")]),
+        );
+
+        resolve_sso_sessions(&mut profile_map);
+
+        let dev = &profile_map["dev"];
+        assert_eq!(
+            dev.get("sso_start_url").map(String::as_str),
+            Some("https://example.awsapps.com/start")
+        );
+        assert_eq!(dev.get("sso_region").map(String::as_str), Some("us-east-1"));
+        // A value the profile already set directly is not overwritten by the session block.
+        assert_eq!(
+            dev.get("sso_account_id").map(String::as_str),
+            Some("123456789012")
+        );
+    }
+
+    #[test]
+    fn test_parse_config_file() {
+        let path = std::env::temp_dir().join("sts_profile_auth_test_parse_config_file.ini");
+        std::fs::write(
+            &path,
+            "[profile dev]\nregion = us-east-1\n\n[sso-session my-sso]\nsso_region = us-west-2\n",
+        )
+        .expect("failed to write test config file");
+
+        let profile_map = parse_config_file(&path).expect("file should parse");
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(
+            profile_map["dev"].get("region").map(String::as_str),
+            Some("us-east-1")
+        );
+        // The `[sso-session name]` header is kept verbatim, not folded to just "name".
+        assert_eq!(
+            profile_map["sso-session my-sso"]
+                .get("sso_region")
+                .map(String::as_str),
+            Some("us-west-2")
+        );
+    }
+
+    #[test]
+    fn test_cached_session_credentials_is_valid() {
+        let fresh = CachedSessionCredentials {
+            access_key_id: "KEY".to_string(),
+            secret_access_key: "SECRET".to_string(),
+            session_token: "TOKEN".to_string(),
+            expiration: (chrono::Utc::now() + chrono::Duration::hours(1)).to_rfc3339(),
+        };
+        assert!(fresh.is_valid());
+
+        let expired = CachedSessionCredentials {
+            expiration: (chrono::Utc::now() - chrono::Duration::hours(1)).to_rfc3339(),
+            ..fresh.clone()
+        };
+        assert!(!expired.is_valid());
+
+        // Within the 1-minute expiry margin counts as no longer valid.
+        let about_to_expire = CachedSessionCredentials {
+            expiration: (chrono::Utc::now() + chrono::Duration::seconds(30)).to_rfc3339(),
+            ..fresh
+        };
+        assert!(!about_to_expire.is_valid());
+    }
 }